@@ -1,6 +1,6 @@
 use serialport::{SerialPort, SerialPortType};
 use std::io::{self, Read, Write};
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
@@ -13,20 +13,209 @@ pub enum Error {
 
     #[error("no device")]
     NoDevice,
+    #[error("unexpected response")]
+    UnexpectedResponse,
+}
+
+/// Selects which serial port [`DeviceBuilder::open`] should connect to.
+pub enum PortFilter<'a> {
+    VidPid { vid: u16, pid: u16 },
+    Serial(&'a str),
+    PortName(&'a str),
+}
+
+impl PortFilter<'_> {
+    fn matches(&self, port_info: &serialport::SerialPortInfo) -> bool {
+        match self {
+            Self::VidPid { vid, pid } => matches!(
+                &port_info.port_type,
+                SerialPortType::UsbPort(usb) if usb.vid == *vid && usb.pid == *pid
+            ),
+            Self::Serial(serial) => matches!(
+                &port_info.port_type,
+                SerialPortType::UsbPort(usb) if usb.serial_number.as_deref() == Some(*serial)
+            ),
+            Self::PortName(port_name) => port_info.port_name == *port_name,
+        }
+    }
+}
+
+/// A serial port matching a [`PortFilter::VidPid`] scan, together with the
+/// USB descriptor strings the connected chip reports for itself.
+#[derive(Debug, Clone)]
+pub struct DeviceInfo {
+    pub port_name: String,
+    pub serial_number: Option<String>,
+    pub vendor: String,
+    pub product: String,
+    pub serial: String,
+}
+
+pub struct DeviceBuilder {
+    addr: u8,
+    read_timeout: Duration,
+    write_timeout: Duration,
+    retries: u32,
+    retry_backoff: Duration,
+    keepalive_interval: Option<Duration>,
+}
+
+impl Default for DeviceBuilder {
+    fn default() -> Self {
+        Self {
+            addr: 0x00,
+            read_timeout: Duration::from_millis(500),
+            write_timeout: Duration::from_millis(500),
+            retries: 0,
+            retry_backoff: Duration::from_millis(50),
+            keepalive_interval: None,
+        }
+    }
+}
+
+impl DeviceBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn addr(mut self, addr: u8) -> Self {
+        self.addr = addr;
+        self
+    }
+
+    pub fn read_timeout(mut self, timeout: Duration) -> Self {
+        self.read_timeout = timeout;
+        self
+    }
+
+    pub fn write_timeout(mut self, timeout: Duration) -> Self {
+        self.write_timeout = timeout;
+        self
+    }
+
+    pub fn retries(mut self, retries: u32) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    pub fn retry_backoff(mut self, backoff: Duration) -> Self {
+        self.retry_backoff = backoff;
+        self
+    }
+
+    pub fn keepalive_interval(mut self, interval: Duration) -> Self {
+        self.keepalive_interval = Some(interval);
+        self
+    }
+
+    pub fn build<P>(self, port: P) -> Device<P> {
+        Device {
+            port,
+            buf: [0; ch9329::MAX_PACKET_SIZE],
+            decoder: ch9329::Decoder::new(),
+            addr: self.addr,
+            read_timeout: self.read_timeout,
+            write_timeout: self.write_timeout,
+            retries: self.retries,
+            retry_backoff: self.retry_backoff,
+            keepalive_interval: self.keepalive_interval,
+            last_activity: None,
+        }
+    }
+
+    pub fn open(self, filter: PortFilter) -> Result<Device<BoxedSerialPort>, Error> {
+        let port_info = serialport::available_ports()?
+            .into_iter()
+            .find(|port_info| filter.matches(port_info))
+            .ok_or(Error::NoDevice)?;
+        let port = serialport::new(port_info.port_name, 9_600)
+            .timeout(self.read_timeout)
+            .open()?;
+        Ok(self.build(BoxedSerialPort(port)))
+    }
+
+    pub fn open_usb(self, vid: u16, pid: u16) -> Result<Device<BoxedSerialPort>, Error> {
+        self.open(PortFilter::VidPid { vid, pid })
+    }
 }
 
 pub struct Device<P> {
     port: P,
     buf: [u8; ch9329::MAX_PACKET_SIZE],
+    decoder: ch9329::Decoder,
     addr: u8,
+    read_timeout: Duration,
+    write_timeout: Duration,
+    retries: u32,
+    retry_backoff: Duration,
+    keepalive_interval: Option<Duration>,
+    last_activity: Option<Instant>,
+}
+
+/// The slice of [`SerialPort`] this crate actually needs from `P`, factored
+/// out so [`Device`]'s main `impl` block can stay on `Read + Write` instead
+/// of `SerialPort` directly.
+///
+/// `Box<dyn SerialPort>` doesn't implement `SerialPort` itself — `serialport`
+/// only provides that for `&mut T`, not `Box<T>` — so [`BoxedSerialPort`]
+/// wraps it for [`DeviceBuilder::open`]/[`Device::open_usb`] to hand back
+/// instead. A blanket `impl<P: SerialPort> SetTimeout for P` plus a second
+/// impl for the bare `Box<dyn SerialPort>` would conflict (E0119): `SerialPort`
+/// is foreign, so rustc can't rule out some upstream version implementing it
+/// for `Box<dyn SerialPort>` too. Implementing it for our own local
+/// [`BoxedSerialPort`] newtype instead sidesteps that — nothing outside this
+/// crate can add a conflicting impl for a type it doesn't own.
+pub trait SetTimeout {
+    fn set_timeout(&mut self, timeout: Duration) -> Result<(), serialport::Error>;
+}
+
+impl<P> SetTimeout for P
+where
+    P: SerialPort,
+{
+    fn set_timeout(&mut self, timeout: Duration) -> Result<(), serialport::Error> {
+        SerialPort::set_timeout(self, timeout)
+    }
+}
+
+/// A boxed serial port, as returned by [`DeviceBuilder::open`]/
+/// [`Device::open_usb`].
+///
+/// `Box<dyn SerialPort>` can't implement [`SerialPort`] itself (`serialport`
+/// only provides that blanket impl for `&mut T`), so this newtype stands in
+/// for it as `Device`'s type parameter.
+pub struct BoxedSerialPort(Box<dyn SerialPort>);
+
+impl Read for BoxedSerialPort {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.0.read(buf)
+    }
+}
+
+impl Write for BoxedSerialPort {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
+
+impl SetTimeout for BoxedSerialPort {
+    fn set_timeout(&mut self, timeout: Duration) -> Result<(), serialport::Error> {
+        self.0.set_timeout(timeout)
+    }
 }
 
 impl<P> Device<P>
 where
-    P: Read + Write,
+    P: Read + Write + SetTimeout,
 {
     #[tracing::instrument(err, ret, skip(self))]
     pub fn clear(&mut self) -> Result<usize, Error> {
+        self.decoder = ch9329::Decoder::new();
+        self.port.set_timeout(self.read_timeout)?;
         let mut len = 0;
         loop {
             match self.port.read(&mut self.buf) {
@@ -38,55 +227,226 @@ where
         }
     }
 
+    /// Number of stray bytes the framing decoder has had to discard while
+    /// resynchronizing on the stream so far.
+    pub fn discarded(&self) -> usize {
+        self.decoder.discarded()
+    }
+
+    /// Targets a different unit on a shared multi-device bus.
+    pub fn with_addr(mut self, addr: u8) -> Self {
+        self.addr = addr;
+        self
+    }
+
+    pub fn set_addr(&mut self, addr: u8) {
+        self.addr = addr;
+    }
+
+    pub fn addr(&self) -> u8 {
+        self.addr
+    }
+
     #[tracing::instrument(err, ret, skip(self))]
     pub fn send(&mut self, command: ch9329::Command) -> Result<(), Error> {
         let packet = ch9329::encode(&mut self.buf, self.addr, command.cmd(), |buf| {
             command.data(buf)
-        });
+        })?;
         tracing::info!(packet = format_args!("{packet:02X?}"));
+        self.port.set_timeout(self.write_timeout)?;
         self.port.write_all(packet)?;
         self.port.flush()?;
+        self.last_activity = Some(Instant::now());
         Ok(())
     }
 
-    #[tracing::instrument(err, ret, skip(self))]
-    pub fn recv(&mut self) -> Result<(u8, ch9329::Response<'_>), Error> {
+    /// Reads frames until one addressed to `self.addr` arrives, copying its
+    /// data into `self.buf` and returning its `cmd` and the data's length.
+    ///
+    /// Frames are copied out of the decoder (rather than decoded in place)
+    /// so the address-mismatch case below can keep looping: the decoder is
+    /// reborrowed on every iteration, and a `Response<'_>` tied to that
+    /// reborrow would conflict with looping back to it again.
+    fn recv_frame(&mut self) -> Result<(u8, usize), Error> {
         // https://github.com/tokio-rs/tracing/issues/2796
         let this = self;
-        let mut len = 0;
-        while let Err(ch9329::Error::Incomplete(total)) = ch9329::decode(&this.buf[..len]) {
-            let n = this.port.read(&mut this.buf[len..total])?;
+        this.port.set_timeout(this.read_timeout)?;
+        let mut chunk = [0; ch9329::MAX_PACKET_SIZE];
+        let mut pushed: &[u8] = &[];
+        loop {
+            // Drain any frame the decoder already buffered before reading
+            // more, so a single read that picks up two frames at once isn't
+            // stalled on.
+            if let Some((addr, cmd, data)) = this.decoder.push(pushed) {
+                pushed = &[];
+                if addr != this.addr {
+                    // On a shared multi-device bus, only one unit's replies
+                    // are ours; quietly drop the rest and keep reading.
+                    tracing::warn!(
+                        addr,
+                        expected = this.addr,
+                        "dropping frame for another unit"
+                    );
+                    continue;
+                }
+                tracing::info!(cmd, data = format_args!("{data:02X?}"));
+                let len = data.len();
+                this.buf[..len].copy_from_slice(data);
+                return Ok((cmd, len));
+            }
+            let n = this.port.read(&mut chunk)?;
             if n == 0 {
                 return Err(Error::Io(io::ErrorKind::UnexpectedEof.into()));
             }
-            len += n;
+            pushed = &chunk[..n];
         }
-        let packet = &this.buf[..len];
-        tracing::info!(packet = format_args!("{packet:02X?}"));
-        let (addr, cmd, data) = ch9329::decode(packet)?;
-        Ok((addr, ch9329::Response::decode(cmd, data)?))
+    }
+
+    #[tracing::instrument(err, ret, skip(self))]
+    pub fn recv(&mut self) -> Result<(u8, ch9329::Response<'_>), Error> {
+        // https://github.com/tokio-rs/tracing/issues/2796
+        let this = self;
+        let (cmd, len) = this.recv_frame()?;
+        Ok((this.addr, ch9329::Response::decode(cmd, &this.buf[..len])?))
+    }
+
+    fn query_usb_string(&mut self, type_: ch9329::UsbStringType) -> Result<String, Error> {
+        self.send(ch9329::Command::GetUsbString { type_ })?;
+        match self.recv()?.1 {
+            ch9329::Response::GetUsbString { descriptor, .. } => Ok(descriptor.to_owned()),
+            _ => Err(Error::UnexpectedResponse),
+        }
+    }
+
+    // Sends `GetInfo` to confirm the link is alive and re-synchronize the
+    // remote side, but only once `keepalive_interval` has elapsed since the
+    // last `send`. Goes through the same retry/backoff path as `execute` so
+    // a transient glitch on the ping doesn't abort the real command.
+    fn keepalive(&mut self) -> Result<(), Error> {
+        let due = match (self.keepalive_interval, self.last_activity) {
+            (Some(interval), Some(last_activity)) => last_activity.elapsed() >= interval,
+            (Some(_), None) => false,
+            (None, _) => false,
+        };
+        if due {
+            self.send_recv_retrying(ch9329::Command::GetInfo)?;
+        }
+        Ok(())
+    }
+
+    /// Sends `command` and waits for its response, transparently re-sending
+    /// it (with backoff) up to `retries` times when `recv` hits a transient
+    /// `Io(TimedOut)`/`Io(UnexpectedEof)` or the chip reports
+    /// `ErrTimeout`/`ErrSum`.
+    #[tracing::instrument(err, skip(self))]
+    pub fn execute(
+        &mut self,
+        command: ch9329::Command,
+    ) -> Result<(u8, ch9329::Response<'_>), Error> {
+        // https://github.com/tokio-rs/tracing/issues/2796
+        let this = self;
+        this.keepalive()?;
+        this.send_recv_retrying(command)
+    }
+
+    fn send_recv_retrying(
+        &mut self,
+        command: ch9329::Command,
+    ) -> Result<(u8, ch9329::Response<'_>), Error> {
+        let mut backoff = self.retry_backoff;
+        // Deciding whether an attempt should be retried only needs the
+        // owned `(cmd, len)` pair `recv_frame` hands back; doing that inside
+        // the loop and decoding the winning `Response<'_>` only once outside
+        // of it keeps the loop from having to carry a self-borrow across
+        // iterations (see recv_frame's doc comment).
+        let (cmd, len) = 'done: {
+            for attempt in 0..=self.retries {
+                self.send(command)?;
+                match self.recv_frame() {
+                    Ok((cmd, len)) => {
+                        let transient = matches!(
+                            ch9329::Response::decode(cmd, &self.buf[..len])?.status(),
+                            Some(
+                                ch9329::CommandExecutionStatus::ErrTimeout
+                                    | ch9329::CommandExecutionStatus::ErrSum
+                            )
+                        );
+                        if transient && attempt < self.retries {
+                            std::thread::sleep(backoff);
+                            backoff *= 2;
+                            continue;
+                        }
+                        break 'done (cmd, len);
+                    }
+                    // A blocking read that simply times out with no bytes at
+                    // all is the common failure mode on a flaky USB-serial
+                    // link; a zero-byte read (`UnexpectedEof`) is rarer but
+                    // just as transient. Anything else (a malformed frame, a
+                    // hard port error) isn't worth retrying.
+                    Err(Error::Io(e))
+                        if matches!(
+                            e.kind(),
+                            io::ErrorKind::TimedOut | io::ErrorKind::UnexpectedEof
+                        ) =>
+                    {
+                        if attempt < self.retries {
+                            std::thread::sleep(backoff);
+                            backoff *= 2;
+                            continue;
+                        }
+                        return Err(Error::Io(e));
+                    }
+                    Err(e) => return Err(e),
+                }
+            }
+            unreachable!("the attempt == retries iteration always returns")
+        };
+        Ok((self.addr, ch9329::Response::decode(cmd, &self.buf[..len])?))
     }
 }
 
-impl Device<Box<dyn SerialPort>> {
+impl Device<BoxedSerialPort> {
     pub fn open_usb(vid: u16, pid: u16) -> Result<Self, Error> {
-        let port_info = serialport::available_ports()?
+        DeviceBuilder::new().open_usb(vid, pid)
+    }
+
+    /// Lists the serial ports matching `vid`/`pid`, opening each briefly to
+    /// read back its `GetUsbString` vendor/product/serial descriptors.
+    ///
+    /// A port that fails to open or answer (busy, no permissions, a
+    /// non-CH9329 device sharing the vid/pid) is logged and skipped rather
+    /// than failing the whole scan.
+    pub fn available_devices(vid: u16, pid: u16) -> Result<Vec<DeviceInfo>, Error> {
+        let filter = PortFilter::VidPid { vid, pid };
+        Ok(serialport::available_ports()?
             .into_iter()
-            .find(|port_info| {
-                if let SerialPortType::UsbPort(port_info) = &port_info.port_type {
-                    port_info.vid == vid && port_info.pid == pid
-                } else {
-                    false
+            .filter(|port_info| filter.matches(port_info))
+            .filter_map(|port_info| match Self::probe(&port_info) {
+                Ok(info) => Some(info),
+                Err(error) => {
+                    tracing::warn!(port_name = port_info.port_name, %error, "skipping device");
+                    None
                 }
             })
-            .ok_or(Error::NoDevice)?;
-        let port = serialport::new(port_info.port_name, 9_600)
+            .collect())
+    }
+
+    fn probe(port_info: &serialport::SerialPortInfo) -> Result<DeviceInfo, Error> {
+        let serial_number = match &port_info.port_type {
+            SerialPortType::UsbPort(usb) => usb.serial_number.clone(),
+            _ => None,
+        };
+        let port = serialport::new(&port_info.port_name, 9_600)
             .timeout(Duration::from_millis(500))
             .open()?;
-        Ok(Self {
-            port,
-            buf: [0; ch9329::MAX_PACKET_SIZE],
-            addr: 0x00,
+        let mut device = DeviceBuilder::new().build(BoxedSerialPort(port));
+        device.clear()?;
+        Ok(DeviceInfo {
+            vendor: device.query_usb_string(ch9329::UsbStringType::Vendor)?,
+            product: device.query_usb_string(ch9329::UsbStringType::Product)?,
+            serial: device.query_usb_string(ch9329::UsbStringType::Serial)?,
+            port_name: port_info.port_name.clone(),
+            serial_number,
         })
     }
 }
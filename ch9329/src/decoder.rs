@@ -0,0 +1,139 @@
+use crate::{decode, Error, HEAD, MAX_PACKET_SIZE};
+
+/// A self-synchronizing framer sitting on top of [`decode`].
+///
+/// Feed it raw bytes as they arrive with [`Decoder::push`]; it buffers a
+/// partial frame across calls and, unlike a bare [`decode`], recovers from a
+/// stray byte or a corrupted length/sum instead of wedging on
+/// [`Error::InvalidHead`]/[`Error::InvalidSum`] forever.
+pub struct Decoder {
+    buf: [u8; MAX_PACKET_SIZE],
+    len: usize,
+    out: [u8; MAX_PACKET_SIZE],
+    discarded: usize,
+}
+
+impl Default for Decoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Decoder {
+    pub const fn new() -> Self {
+        Self {
+            buf: [0; MAX_PACKET_SIZE],
+            len: 0,
+            out: [0; MAX_PACKET_SIZE],
+            discarded: 0,
+        }
+    }
+
+    /// Total number of bytes dropped so far while resynchronizing.
+    pub fn discarded(&self) -> usize {
+        self.discarded
+    }
+
+    /// Appends `data` and, if a full frame is now available, returns it.
+    ///
+    /// Call again with an empty slice to keep draining frames that were
+    /// already buffered from a previous call.
+    pub fn push(&mut self, data: &[u8]) -> Option<(u8, u8, &[u8])> {
+        for &b in data {
+            if self.len == self.buf.len() {
+                // No valid frame in a full buffer: slide the window so new
+                // bytes keep arriving instead of stalling forever.
+                self.discard(1);
+            }
+            self.buf[self.len] = b;
+            self.len += 1;
+        }
+
+        loop {
+            match decode(&self.buf[..self.len]) {
+                Ok((_, _, data)) => {
+                    let frame_len = 5 + data.len() + 1;
+                    self.out[..frame_len].copy_from_slice(&self.buf[..frame_len]);
+                    self.buf.copy_within(frame_len..self.len, 0);
+                    self.len -= frame_len;
+                    return decode(&self.out[..frame_len]).ok();
+                }
+                Err(Error::Incomplete(_)) => return None,
+                Err(Error::InvalidHead) => match find(&self.buf[1..self.len], &HEAD) {
+                    Some(pos) => self.discard(1 + pos),
+                    None => {
+                        // The last byte might be the start of the next HEAD;
+                        // keep it around instead of discarding it too.
+                        let keep = usize::from(self.buf[self.len - 1] == HEAD[0]);
+                        self.discard(self.len - keep);
+                    }
+                },
+                Err(Error::InvalidSum) => self.discard(1),
+                Err(Error::InvalidCmd | Error::InvalidData | Error::Utf8(_)) => return None,
+            }
+        }
+    }
+
+    fn discard(&mut self, n: usize) {
+        self.buf.copy_within(n..self.len, 0);
+        self.len -= n;
+        self.discarded += n;
+    }
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Decoder;
+
+    const FRAME: [u8; 14] = [
+        0x57, 0xAB, 0x00, 0x02, 0x08, 0x00, 0x00, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00, 0x10,
+    ];
+
+    #[test]
+    fn test_push_whole_frame() {
+        let mut decoder = Decoder::new();
+        assert_eq!(
+            decoder.push(&FRAME),
+            Some((0x00, 0x02, &[0x00, 0x00, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00][..]))
+        );
+        assert_eq!(decoder.discarded(), 0);
+    }
+
+    #[test]
+    fn test_push_split_frame() {
+        let mut decoder = Decoder::new();
+        assert_eq!(decoder.push(&FRAME[..6]), None);
+        assert_eq!(
+            decoder.push(&FRAME[6..]),
+            Some((0x00, 0x02, &[0x00, 0x00, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00][..]))
+        );
+    }
+
+    #[test]
+    fn test_resync_after_garbage_prefix() {
+        let mut decoder = Decoder::new();
+        assert_eq!(decoder.push(&[0xFF, 0xFF, 0xFF]), None);
+        assert_eq!(
+            decoder.push(&FRAME),
+            Some((0x00, 0x02, &[0x00, 0x00, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00][..]))
+        );
+        assert_eq!(decoder.discarded(), 3);
+    }
+
+    #[test]
+    fn test_resync_after_corrupted_sum() {
+        let mut decoder = Decoder::new();
+        let mut corrupted = FRAME;
+        corrupted[13] = 0x00;
+        assert_eq!(decoder.push(&corrupted), None);
+        assert_eq!(
+            decoder.push(&FRAME),
+            Some((0x00, 0x02, &[0x00, 0x00, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00][..]))
+        );
+        assert!(decoder.discarded() > 0);
+    }
+}
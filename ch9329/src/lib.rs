@@ -2,14 +2,16 @@
 
 #![no_std]
 
+mod decoder;
 mod key_code;
 
 use core::iter;
 use core::str::Utf8Error;
+pub use decoder::Decoder;
 pub use key_code::KeyCode;
 
 pub const MAX_PACKET_SIZE: usize = 5 + 64 + 1;
-const HEAD: [u8; 2] = [0x57, 0xAB];
+pub(crate) const HEAD: [u8; 2] = [0x57, 0xAB];
 
 #[derive(Clone, Copy, Debug, PartialEq, thiserror::Error)]
 pub enum Error {
@@ -28,17 +30,24 @@ pub enum Error {
     InvalidSum,
 }
 
-pub fn encode<F>(buf: &mut [u8], addr: u8, cmd: u8, data: F) -> &[u8]
+pub fn encode<F>(buf: &mut [u8], addr: u8, cmd: u8, data: F) -> Result<&[u8], Error>
 where
-    F: FnOnce(&mut [u8]) -> usize,
+    F: FnOnce(&mut [u8]) -> Result<usize, Error>,
 {
     buf[..2].copy_from_slice(&HEAD);
     buf[2] = addr;
     buf[3] = cmd;
-    let len = data(&mut buf[5..]);
-    buf[4] = len.try_into().unwrap();
-    buf[5 + len] = sum(&buf[..5 + len]);
-    &buf[..5 + len + 1]
+    let len = data(&mut buf[5..])?;
+    buf[4] = len.try_into().map_err(|_| Error::InvalidData)?;
+    // `data` is handed `&mut buf[5..]`, one byte roomier than the real
+    // per-frame data budget (that slice also covers the checksum byte below),
+    // so a `len` it returns isn't guaranteed to leave room for the checksum:
+    // index checked rather than direct so a bug in some `data` impl turns
+    // into `InvalidData` instead of a panic.
+    let frame_len = 5 + len;
+    let checksum = sum(buf.get(..frame_len).ok_or(Error::InvalidData)?);
+    *buf.get_mut(frame_len).ok_or(Error::InvalidData)? = checksum;
+    Ok(&buf[..frame_len + 1])
 }
 
 pub fn decode(buf: &[u8]) -> Result<(u8, u8, &[u8]), Error> {
@@ -76,10 +85,31 @@ pub enum Command<'a> {
     SendMyHidData {
         data: &'a [u8],
     },
+    SendMsAbsData {
+        x: u16,
+        y: u16,
+        buttons: MouseButtons,
+        wheel: i8,
+    },
+    SendMsRelData {
+        dx: i8,
+        dy: i8,
+        buttons: MouseButtons,
+        wheel: i8,
+    },
     GetParaCfg,
+    SetParaCfg {
+        cfg: ParaCfg,
+    },
     GetUsbString {
         type_: UsbStringType,
     },
+    SetUsbString {
+        type_: UsbStringType,
+        descriptor: &'a str,
+    },
+    Reset,
+    RestoreDefaultCfg,
 }
 
 #[derive(Clone, Copy, Debug, PartialEq)]
@@ -88,11 +118,17 @@ pub enum Response<'a> {
         version: char,
     },
     SendKbGeneralData(CommandExecutionStatus),
+    SendMsAbsData(CommandExecutionStatus),
+    SendMsRelData(CommandExecutionStatus),
     GetParaCfg(ParaCfg),
+    SetParaCfg(CommandExecutionStatus),
     GetUsbString {
         type_: UsbStringType,
         descriptor: &'a str,
     },
+    SetUsbString(CommandExecutionStatus),
+    Reset(CommandExecutionStatus),
+    RestoreDefaultCfg(CommandExecutionStatus),
 }
 
 bitflags::bitflags! {
@@ -109,6 +145,27 @@ bitflags::bitflags! {
     }
 }
 
+bitflags::bitflags! {
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    pub struct MouseButtons: u8 {
+        const LEFT = 1 << 0;
+        const RIGHT = 1 << 1;
+        const MIDDLE = 1 << 2;
+    }
+}
+
+/// Maps a point from a caller-supplied logical `resolution` (width, height)
+/// into the 0..=4095 absolute coordinate range `Command::SendMsAbsData`
+/// expects.
+pub fn scale_to_absolute(x: u16, y: u16, resolution: (u16, u16)) -> (u16, u16) {
+    fn scale(value: u16, extent: u16) -> u16 {
+        let extent = u32::from(extent.saturating_sub(1)).max(1);
+        let value = u32::from(value).min(extent);
+        ((value * 4095) / extent) as u16
+    }
+    (scale(x, resolution.0), scale(y, resolution.1))
+}
+
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub enum CommandExecutionStatus {
     Success,
@@ -141,10 +198,79 @@ pub struct ParaCfg {
     pub serial_communication_mode: u8,
     pub addr: u8,
     pub baud_rate: u32,
-    todo_0: [u8; 2 + 2],
+    /// Delay between successive packets on the serial link, in milliseconds.
+    pub packet_interval_ms: u16,
+    // Not yet reverse-engineered; carried through unchanged so a
+    // read-modify-write round trip doesn't clobber it.
+    reserved_0: [u8; 2],
     pub vid: u16,
     pub pid: u16,
-    todo_1: [u8; 2 + 2 + 1 + 8 + 8 + 1 + 1 + 12],
+    /// How often, in milliseconds, a held key is re-reported while a key is
+    /// down.
+    pub keyboard_upload_interval_ms: u16,
+    /// Delay, in milliseconds, before a key-up is reported after release.
+    pub keyboard_release_delay_ms: u16,
+    /// Whether the chip automatically "presses enter" after typing a custom
+    /// descriptor string.
+    pub auto_enter: bool,
+    /// Custom USB descriptor string burned in alongside `vid`/`pid`.
+    pub custom_descriptor_1: [u8; 8],
+    /// A second custom USB descriptor string.
+    pub custom_descriptor_2: [u8; 8],
+    pub custom_descriptor_1_enabled: bool,
+    pub custom_descriptor_2_enabled: bool,
+    // Trailing padding; genuinely undocumented.
+    reserved_1: [u8; 12],
+}
+
+impl ParaCfg {
+    pub const fn new(
+        operation_mode: u8,
+        serial_communication_mode: u8,
+        addr: u8,
+        baud_rate: u32,
+        vid: u16,
+        pid: u16,
+    ) -> Self {
+        Self {
+            operation_mode,
+            serial_communication_mode,
+            addr,
+            baud_rate,
+            packet_interval_ms: 0,
+            reserved_0: [0; 2],
+            vid,
+            pid,
+            keyboard_upload_interval_ms: 0,
+            keyboard_release_delay_ms: 0,
+            auto_enter: false,
+            custom_descriptor_1: [0; 8],
+            custom_descriptor_2: [0; 8],
+            custom_descriptor_1_enabled: false,
+            custom_descriptor_2_enabled: false,
+            reserved_1: [0; 12],
+        }
+    }
+
+    fn encode(self, buf: &mut [u8]) -> usize {
+        buf[0] = self.operation_mode;
+        buf[1] = self.serial_communication_mode;
+        buf[2] = self.addr;
+        buf[3..7].copy_from_slice(&self.baud_rate.to_be_bytes());
+        buf[7..9].copy_from_slice(&self.packet_interval_ms.to_be_bytes());
+        buf[9..11].copy_from_slice(&self.reserved_0);
+        buf[11..13].copy_from_slice(&self.vid.to_be_bytes());
+        buf[13..15].copy_from_slice(&self.pid.to_be_bytes());
+        buf[15..17].copy_from_slice(&self.keyboard_upload_interval_ms.to_be_bytes());
+        buf[17..19].copy_from_slice(&self.keyboard_release_delay_ms.to_be_bytes());
+        buf[19] = self.auto_enter as u8;
+        buf[20..28].copy_from_slice(&self.custom_descriptor_1);
+        buf[28..36].copy_from_slice(&self.custom_descriptor_2);
+        buf[36] = self.custom_descriptor_1_enabled as u8;
+        buf[37] = self.custom_descriptor_2_enabled as u8;
+        buf[38..50].copy_from_slice(&self.reserved_1);
+        50
+    }
 }
 
 #[derive(Clone, Copy, Debug, PartialEq)]
@@ -154,20 +280,46 @@ pub enum UsbStringType {
     Serial,
 }
 
+impl UsbStringType {
+    fn to_u8(self) -> u8 {
+        match self {
+            Self::Vendor => 0x00,
+            Self::Product => 0x01,
+            Self::Serial => 0x02,
+        }
+    }
+
+    fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0x00 => Some(Self::Vendor),
+            0x01 => Some(Self::Product),
+            0x02 => Some(Self::Serial),
+            _ => None,
+        }
+    }
+}
+
 impl Command<'_> {
     pub fn cmd(self) -> u8 {
         match self {
             Self::GetInfo => 0x01,
             Self::SendKbGeneralData { .. } => 0x02,
+            Self::SendMsAbsData { .. } => 0x04,
+            Self::SendMsRelData { .. } => 0x05,
             Self::SendMyHidData { .. } => 0x06,
             Self::GetParaCfg => 0x08,
+            Self::SetParaCfg { .. } => 0x09,
             Self::GetUsbString { .. } => 0x0A,
+            Self::SetUsbString { .. } => 0x0B,
+            Self::RestoreDefaultCfg => 0x0C,
+            Self::Reset => 0x0F,
         }
     }
 
-    pub fn data(self, buf: &mut [u8]) -> usize {
-        match self {
-            Self::GetInfo | Self::GetParaCfg => 0,
+    pub fn data(self, buf: &mut [u8]) -> Result<usize, Error> {
+        Ok(match self {
+            Self::GetInfo | Self::GetParaCfg | Self::Reset | Self::RestoreDefaultCfg => 0,
+            Self::SetParaCfg { cfg } => cfg.encode(buf),
             Self::SendKbGeneralData { modifiers, codes } => {
                 buf[0] = modifiers.bits();
                 buf[1] = 0x00;
@@ -185,14 +337,69 @@ impl Command<'_> {
                 buf[..data.len()].copy_from_slice(data);
                 data.len()
             }
+            Self::SendMsAbsData {
+                x,
+                y,
+                buttons,
+                wheel,
+            } => {
+                // 0x02 marks this report as absolute-coordinate mouse data.
+                buf[0] = 0x02;
+                buf[1] = buttons.bits();
+                buf[2..4].copy_from_slice(&x.to_le_bytes());
+                buf[4..6].copy_from_slice(&y.to_le_bytes());
+                buf[6] = wheel as u8;
+                7
+            }
+            Self::SendMsRelData {
+                dx,
+                dy,
+                buttons,
+                wheel,
+            } => {
+                // 0x01 marks this report as relative-coordinate mouse data.
+                buf[0] = 0x01;
+                buf[1] = buttons.bits();
+                buf[2] = dx as u8;
+                buf[3] = dy as u8;
+                buf[4] = wheel as u8;
+                5
+            }
             Self::GetUsbString { type_ } => {
-                buf[0] = match type_ {
-                    UsbStringType::Vendor => 0x00,
-                    UsbStringType::Product => 0x01,
-                    UsbStringType::Serial => 0x02,
-                };
+                buf[0] = type_.to_u8();
                 1
             }
+            Self::SetUsbString { type_, descriptor } => {
+                buf[0] = type_.to_u8();
+                let descriptor = descriptor.as_bytes();
+                // Unlike the fixed-size descriptor fields in `ParaCfg`, this
+                // comes straight from a caller-supplied `&str`: reject it
+                // with `InvalidData` instead of panicking when it doesn't
+                // fit in the packet (or overflows the length byte).
+                let len = u8::try_from(descriptor.len()).map_err(|_| Error::InvalidData)?;
+                let dst = buf
+                    .get_mut(2..2 + descriptor.len())
+                    .ok_or(Error::InvalidData)?;
+                dst.copy_from_slice(descriptor);
+                buf[1] = len;
+                2 + descriptor.len()
+            }
+        })
+    }
+}
+
+impl Response<'_> {
+    /// The chip's execution status, for the responses that carry one.
+    pub fn status(&self) -> Option<CommandExecutionStatus> {
+        match self {
+            Self::SendKbGeneralData(status)
+            | Self::SendMsAbsData(status)
+            | Self::SendMsRelData(status)
+            | Self::SetParaCfg(status)
+            | Self::SetUsbString(status)
+            | Self::Reset(status)
+            | Self::RestoreDefaultCfg(status) => Some(*status),
+            _ => None,
         }
     }
 }
@@ -217,38 +424,78 @@ impl<'a> Response<'a> {
                     Err(Error::InvalidData)
                 }
             }
+            0x84 => {
+                if data.len() == 1 {
+                    let status =
+                        CommandExecutionStatus::from_u8(data[0]).ok_or(Error::InvalidData)?;
+                    Ok(Self::SendMsAbsData(status))
+                } else {
+                    Err(Error::InvalidData)
+                }
+            }
+            0x85 => {
+                if data.len() == 1 {
+                    let status =
+                        CommandExecutionStatus::from_u8(data[0]).ok_or(Error::InvalidData)?;
+                    Ok(Self::SendMsRelData(status))
+                } else {
+                    Err(Error::InvalidData)
+                }
+            }
             0x88 => {
                 if data.len() == 50 {
                     let operation_mode = data[0];
                     let serial_communication_mode = data[1];
                     let addr = data[2];
                     let baud_rate = u32::from_be_bytes(data[3..7].try_into().unwrap());
-                    let todo_0 = data[7..11].try_into().unwrap();
+                    let packet_interval_ms = u16::from_be_bytes(data[7..9].try_into().unwrap());
+                    let reserved_0 = data[9..11].try_into().unwrap();
                     let vid = u16::from_be_bytes(data[11..13].try_into().unwrap());
                     let pid = u16::from_be_bytes(data[13..15].try_into().unwrap());
-                    let todo_1 = data[15..50].try_into().unwrap();
+                    let keyboard_upload_interval_ms =
+                        u16::from_be_bytes(data[15..17].try_into().unwrap());
+                    let keyboard_release_delay_ms =
+                        u16::from_be_bytes(data[17..19].try_into().unwrap());
+                    let auto_enter = data[19] != 0x00;
+                    let custom_descriptor_1 = data[20..28].try_into().unwrap();
+                    let custom_descriptor_2 = data[28..36].try_into().unwrap();
+                    let custom_descriptor_1_enabled = data[36] != 0x00;
+                    let custom_descriptor_2_enabled = data[37] != 0x00;
+                    let reserved_1 = data[38..50].try_into().unwrap();
                     Ok(Self::GetParaCfg(ParaCfg {
                         operation_mode,
                         serial_communication_mode,
                         addr,
                         baud_rate,
-                        todo_0,
+                        packet_interval_ms,
+                        reserved_0,
                         vid,
                         pid,
-                        todo_1,
+                        keyboard_upload_interval_ms,
+                        keyboard_release_delay_ms,
+                        auto_enter,
+                        custom_descriptor_1,
+                        custom_descriptor_2,
+                        custom_descriptor_1_enabled,
+                        custom_descriptor_2_enabled,
+                        reserved_1,
                     }))
                 } else {
                     Err(Error::InvalidData)
                 }
             }
+            0x89 => {
+                if data.len() == 1 {
+                    let status =
+                        CommandExecutionStatus::from_u8(data[0]).ok_or(Error::InvalidData)?;
+                    Ok(Self::SetParaCfg(status))
+                } else {
+                    Err(Error::InvalidData)
+                }
+            }
             0x8A => {
                 if data.len() >= 2 {
-                    let type_ = match data[0] {
-                        0x00 => Ok(UsbStringType::Vendor),
-                        0x01 => Ok(UsbStringType::Product),
-                        0x02 => Ok(UsbStringType::Serial),
-                        _ => Err(Error::InvalidData),
-                    }?;
+                    let type_ = UsbStringType::from_u8(data[0]).ok_or(Error::InvalidData)?;
                     let len = usize::from(data[1]);
                     if data.len() == 2 + len {
                         let descriptor = core::str::from_utf8(&data[2..2 + len])?;
@@ -260,6 +507,33 @@ impl<'a> Response<'a> {
                     Err(Error::InvalidData)
                 }
             }
+            0x8B => {
+                if data.len() == 1 {
+                    let status =
+                        CommandExecutionStatus::from_u8(data[0]).ok_or(Error::InvalidData)?;
+                    Ok(Self::SetUsbString(status))
+                } else {
+                    Err(Error::InvalidData)
+                }
+            }
+            0x8C => {
+                if data.len() == 1 {
+                    let status =
+                        CommandExecutionStatus::from_u8(data[0]).ok_or(Error::InvalidData)?;
+                    Ok(Self::RestoreDefaultCfg(status))
+                } else {
+                    Err(Error::InvalidData)
+                }
+            }
+            0x8F => {
+                if data.len() == 1 {
+                    let status =
+                        CommandExecutionStatus::from_u8(data[0]).ok_or(Error::InvalidData)?;
+                    Ok(Self::Reset(status))
+                } else {
+                    Err(Error::InvalidData)
+                }
+            }
             _ => Err(Error::InvalidCmd),
         }
     }
@@ -274,13 +548,13 @@ mod tests {
         let data = [0x00, 0x00, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00];
         let packet = super::encode(&mut buf, 0x00, 0x02, |buf| {
             buf[..data.len()].copy_from_slice(&data);
-            data.len()
+            Ok(data.len())
         });
         assert_eq!(
             packet,
-            [
+            Ok(&[
                 0x57, 0xAB, 0x00, 0x02, 0x08, 0x00, 0x00, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00, 0x10
-            ],
+            ][..]),
         );
     }
 
@@ -300,4 +574,157 @@ mod tests {
             ))
         );
     }
+
+    #[test]
+    fn test_para_cfg_roundtrip() {
+        let cfg = super::ParaCfg {
+            packet_interval_ms: 5,
+            keyboard_upload_interval_ms: 10,
+            keyboard_release_delay_ms: 20,
+            auto_enter: true,
+            custom_descriptor_1: *b"Vendor12",
+            custom_descriptor_2: *b"Product1",
+            custom_descriptor_1_enabled: true,
+            custom_descriptor_2_enabled: false,
+            ..super::ParaCfg::new(0x80, 0x00, 0x00, 9_600, 0x1A86, 0x7523)
+        };
+
+        let mut buf = [0; 50];
+        assert_eq!(cfg.encode(&mut buf), 50);
+        assert_eq!(
+            super::Response::decode(0x88, &buf),
+            Ok(super::Response::GetParaCfg(cfg))
+        );
+    }
+
+    #[test]
+    fn test_command_set_usb_string_data() {
+        let mut buf = [0; crate::MAX_PACKET_SIZE];
+        let len = super::Command::SetUsbString {
+            type_: super::UsbStringType::Product,
+            descriptor: "abc",
+        }
+        .data(&mut buf)
+        .unwrap();
+        assert_eq!(len, 5);
+        assert_eq!(buf[..5], [0x01, 0x03, b'a', b'b', b'c']);
+    }
+
+    #[test]
+    fn test_command_set_usb_string_data_too_long() {
+        let mut buf = [0; crate::MAX_PACKET_SIZE];
+        let descriptor = "a".repeat(crate::MAX_PACKET_SIZE);
+        let err = super::Command::SetUsbString {
+            type_: super::UsbStringType::Product,
+            descriptor: &descriptor,
+        }
+        .data(&mut buf)
+        .unwrap_err();
+        assert_eq!(err, super::Error::InvalidData);
+    }
+
+    #[test]
+    fn test_encode_set_usb_string_descriptor_overflowing_frame() {
+        // `descriptor.len() == 63` overflows `data`'s own shifted view into
+        // the frame's checksum byte (`data` is handed one byte more than the
+        // real data budget) without tripping `data`'s own bounds check; only
+        // `encode`, which knows the real frame layout, can catch it.
+        let mut buf = [0; crate::MAX_PACKET_SIZE];
+        let descriptor = "a".repeat(63);
+        let err = super::encode(&mut buf, 0x00, 0x0B, |buf| {
+            super::Command::SetUsbString {
+                type_: super::UsbStringType::Product,
+                descriptor: &descriptor,
+            }
+            .data(buf)
+        })
+        .unwrap_err();
+        assert_eq!(err, super::Error::InvalidData);
+    }
+
+    #[test]
+    fn test_response_decode_statuses() {
+        assert_eq!(
+            super::Response::decode(0x89, &[0x00]),
+            Ok(super::Response::SetParaCfg(
+                super::CommandExecutionStatus::Success
+            ))
+        );
+        assert_eq!(
+            super::Response::decode(0x8B, &[0xE1]),
+            Ok(super::Response::SetUsbString(
+                super::CommandExecutionStatus::ErrTimeout
+            ))
+        );
+        assert_eq!(
+            super::Response::decode(0x8C, &[0x00]),
+            Ok(super::Response::RestoreDefaultCfg(
+                super::CommandExecutionStatus::Success
+            ))
+        );
+        assert_eq!(
+            super::Response::decode(0x8F, &[0x00]),
+            Ok(super::Response::Reset(
+                super::CommandExecutionStatus::Success
+            ))
+        );
+    }
+
+    #[test]
+    fn test_command_send_ms_abs_data() {
+        let mut buf = [0; crate::MAX_PACKET_SIZE];
+        let len = super::Command::SendMsAbsData {
+            x: 0x0102,
+            y: 0x0304,
+            buttons: super::MouseButtons::LEFT | super::MouseButtons::RIGHT,
+            wheel: -1,
+        }
+        .data(&mut buf)
+        .unwrap();
+        assert_eq!(len, 7);
+        assert_eq!(buf[..7], [0x02, 0x03, 0x02, 0x01, 0x04, 0x03, 0xFF]);
+        assert_eq!(
+            super::Response::decode(0x84, &[0x00]),
+            Ok(super::Response::SendMsAbsData(
+                super::CommandExecutionStatus::Success
+            ))
+        );
+    }
+
+    #[test]
+    fn test_command_send_ms_rel_data() {
+        let mut buf = [0; crate::MAX_PACKET_SIZE];
+        let len = super::Command::SendMsRelData {
+            dx: -1,
+            dy: 2,
+            buttons: super::MouseButtons::MIDDLE,
+            wheel: -2,
+        }
+        .data(&mut buf)
+        .unwrap();
+        assert_eq!(len, 5);
+        assert_eq!(buf[..5], [0x01, 0x04, 0xFF, 0x02, 0xFE]);
+        assert_eq!(
+            super::Response::decode(0x85, &[0x00]),
+            Ok(super::Response::SendMsRelData(
+                super::CommandExecutionStatus::Success
+            ))
+        );
+    }
+
+    #[test]
+    fn test_scale_to_absolute() {
+        // A degenerate (or single-point) resolution must not divide by zero;
+        // the origin stays at the origin and anything past it clamps to the
+        // top of the range.
+        assert_eq!(super::scale_to_absolute(0, 0, (0, 0)), (0, 0));
+        assert_eq!(super::scale_to_absolute(5, 5, (1, 1)), (4095, 4095));
+        // The far corner of a real resolution maps to the top of the range.
+        assert_eq!(
+            super::scale_to_absolute(1919, 1079, (1920, 1080)),
+            (4095, 4095)
+        );
+        // The origin always maps to the origin.
+        assert_eq!(super::scale_to_absolute(0, 0, (1920, 1080)), (0, 0));
+    }
 }